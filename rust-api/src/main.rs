@@ -1,18 +1,264 @@
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_cors::Cors;
+use actix_web::dev::Payload;
+use actix_web::{
+    delete, get, post, put, web, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use config::{Config, Environment, File};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::future::{ready, Ready};
 use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Recipe {
+    id: Uuid,
     name: String,
     category: Option<String>,
     method: Option<String>,
     difficulty: Option<String>,
 }
 
+/// The recipe fields a client supplies; the `id` is always assigned by the server.
+#[derive(Deserialize)]
+struct RecipeInput {
+    name: String,
+    category: Option<String>,
+    method: Option<String>,
+    difficulty: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RecipeSummary {
+    id: Uuid,
+    name: String,
+}
+
+/// Filter and pagination parameters for `GET /recipes`. Every field is optional;
+/// an empty query returns the full collection.
+#[derive(Deserialize)]
+struct ListQuery {
+    category: Option<String>,
+    method: Option<String>,
+    difficulty: Option<String>,
+    /// Case-insensitive substring match against the recipe name.
+    q: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl ListQuery {
+    /// Whether the query carries no filters or pagination, in which case the
+    /// response can be served straight from the list cache.
+    fn is_default(&self) -> bool {
+        self.category.is_none()
+            && self.method.is_none()
+            && self.difficulty.is_none()
+            && self.q.is_none()
+            && self.limit.is_none()
+            && self.offset.is_none()
+    }
+
+    fn matches(&self, recipe: &Recipe) -> bool {
+        let field_matches = |filter: &Option<String>, value: &Option<String>| match filter {
+            Some(filter) => value.as_deref() == Some(filter.as_str()),
+            None => true,
+        };
+        field_matches(&self.category, &recipe.category)
+            && field_matches(&self.method, &recipe.method)
+            && field_matches(&self.difficulty, &recipe.difficulty)
+            && match &self.q {
+                Some(needle) => recipe.name.to_lowercase().contains(&needle.to_lowercase()),
+                None => true,
+            }
+    }
+}
+
+/// A page of recipe summaries alongside the total count of matches before
+/// pagination was applied.
+#[derive(Serialize)]
+struct RecipeList {
+    total: usize,
+    results: Vec<RecipeSummary>,
+}
+
+/// A user account as persisted in the `users` tree; the password is stored as an
+/// argon2-encoded hash, never in plain text.
+#[derive(Serialize, Deserialize)]
+struct User {
+    username: String,
+    password_hash: String,
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// JWT claims: `sub` carries the username and `exp` the expiry (seconds since epoch).
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    exp: usize,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    token: String,
+}
+
+/// A mutation notification fanned out to every open `/recipes/events` stream.
+#[derive(Serialize, Clone)]
+struct RecipeEvent {
+    action: &'static str,
+    id: Uuid,
+    name: String,
+}
+
+/// Runtime configuration, loaded from `config.toml` and then overlaid with
+/// `COOKMATE_`-prefixed environment variables so a deployment can be retargeted
+/// without recompiling.
+#[derive(Deserialize, Clone)]
+struct Settings {
+    #[serde(default = "default_bind_host")]
+    bind_host: String,
+    #[serde(default = "default_port")]
+    port: u16,
+    #[serde(default = "default_cors_origin")]
+    cors_origin: String,
+    #[serde(default = "default_jwt_secret")]
+    jwt_secret: String,
+    #[serde(default = "default_token_ttl")]
+    token_ttl: i64,
+}
+
+fn default_bind_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_cors_origin() -> String {
+    "http://localhost:3000".to_string()
+}
+
+fn default_jwt_secret() -> String {
+    "change-me".to_string()
+}
+
+fn default_token_ttl() -> i64 {
+    3600
+}
+
+impl Settings {
+    /// Build the settings from `config.toml` (optional) overlaid with the
+    /// `COOKMATE_` environment, falling back to the field defaults.
+    fn load() -> Self {
+        Config::builder()
+            .add_source(File::with_name("config").required(false))
+            .add_source(Environment::with_prefix("COOKMATE"))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .expect("failed to load settings")
+    }
+}
+
 struct AppState {
-    recipes: Mutex<HashMap<String, Recipe>>,
+    recipes: sled::Tree,
+    users: sled::Tree,
+    jwt_secret: String,
+    token_ttl: i64,
+    events: broadcast::Sender<RecipeEvent>,
+    /// Memoized `(etag, body)` for `GET /recipes`, rebuilt lazily and cleared on
+    /// every mutation.
+    list_cache: Mutex<Option<(String, web::Bytes)>>,
+}
+
+impl AppState {
+    /// Broadcast a mutation to any connected SSE clients, ignoring the error
+    /// that arises when no receivers are currently subscribed, and drop the
+    /// stale list cache so the next read recomputes it.
+    fn notify(&self, action: &'static str, id: Uuid, name: String) {
+        let _ = self.events.send(RecipeEvent { action, id, name });
+        *self.list_cache.lock().unwrap() = None;
+    }
+}
+
+/// Compute the quoted ETag (a SHA-256 digest) for a serialized response body.
+fn etag_for(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    format!("\"{digest:x}\"")
+}
+
+/// If the request's `If-None-Match` matches `etag`, answer `304 Not Modified`;
+/// otherwise return the body with the `ETag` header attached.
+fn conditional_json(req: &HttpRequest, etag: String, body: web::Bytes) -> HttpResponse {
+    let matches = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value == etag)
+        .unwrap_or(false);
+    if matches {
+        return HttpResponse::NotModified().finish();
+    }
+    HttpResponse::Ok()
+        .insert_header(("ETag", etag))
+        .content_type("application/json")
+        .body(body)
+}
+
+/// Extractor that authenticates a request from its `Authorization: Bearer` token.
+///
+/// Handlers that take this as an argument are only reachable with a valid,
+/// unexpired JWT; a missing or malformed token yields `401 Unauthorized`.
+struct AuthenticatedUser {
+    username: String,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let secret = match req.app_data::<web::Data<AppState>>() {
+            Some(state) => state.jwt_secret.clone(),
+            None => {
+                return ready(Err(actix_web::error::ErrorInternalServerError(
+                    "missing app state",
+                )))
+            }
+        };
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let token = match token {
+            Some(token) => token,
+            None => {
+                return ready(Err(actix_web::error::ErrorUnauthorized(
+                    "missing bearer token",
+                )))
+            }
+        };
+        match decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::default(),
+        ) {
+            Ok(data) => ready(Ok(AuthenticatedUser {
+                username: data.claims.sub,
+            })),
+            Err(_) => ready(Err(actix_web::error::ErrorUnauthorized("invalid token"))),
+        }
+    }
 }
 
 #[get("/ping")]
@@ -20,34 +266,291 @@ async fn ping() -> impl Responder {
     HttpResponse::Ok().body("pong")
 }
 
+#[post("/login")]
+async fn login(data: web::Data<AppState>, item: web::Json<Credentials>) -> impl Responder {
+    let creds = item.into_inner();
+    let stored = match data.users.get(creds.username.as_bytes()) {
+        Ok(Some(value)) => value,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let user: User = match serde_json::from_slice(&stored) {
+        Ok(user) => user,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match argon2::verify_encoded(&user.password_hash, creds.password.as_bytes()) {
+        Ok(true) => {}
+        _ => return HttpResponse::Unauthorized().finish(),
+    }
+
+    let exp = (chrono::Utc::now().timestamp() + data.token_ttl) as usize;
+    let claims = Claims {
+        sub: user.username,
+        exp,
+    };
+    match encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(data.jwt_secret.as_bytes()),
+    ) {
+        Ok(token) => HttpResponse::Ok().json(TokenResponse { token }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/me")]
+async fn me(user: AuthenticatedUser) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "username": user.username }))
+}
+
 #[get("/recipes")]
-async fn list_recipes(data: web::Data<AppState>) -> impl Responder {
-    let recipes = data.recipes.lock().unwrap();
-    let names: Vec<String> = recipes.keys().cloned().collect();
-    HttpResponse::Ok().json(names)
+async fn list_recipes(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
+    // The cache only covers the unfiltered, unpaginated response; any query
+    // parameter forces a fresh scan.
+    if query.is_default() {
+        if let Some((etag, body)) = data.list_cache.lock().unwrap().clone() {
+            return conditional_json(&req, etag, body);
+        }
+    }
+
+    let mut matched: Vec<RecipeSummary> = data
+        .recipes
+        .iter()
+        .values()
+        .filter_map(|value| value.ok())
+        .filter_map(|value| serde_json::from_slice::<Recipe>(&value).ok())
+        .filter(|recipe| query.matches(recipe))
+        .map(|recipe| RecipeSummary {
+            id: recipe.id,
+            name: recipe.name,
+        })
+        .collect();
+
+    let total = matched.len();
+    let offset = query.offset.unwrap_or(0).min(total);
+    matched.drain(..offset);
+    if let Some(limit) = query.limit {
+        matched.truncate(limit);
+    }
+
+    let body = match serde_json::to_vec(&RecipeList {
+        total,
+        results: matched,
+    }) {
+        Ok(bytes) => web::Bytes::from(bytes),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let etag = etag_for(&body);
+    if query.is_default() {
+        *data.list_cache.lock().unwrap() = Some((etag.clone(), body.clone()));
+    }
+    conditional_json(&req, etag, body)
+}
+
+#[get("/recipes/{id}")]
+async fn get_recipe(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match data.recipes.get(id.as_bytes()) {
+        Ok(Some(value)) => {
+            let body = web::Bytes::copy_from_slice(&value);
+            conditional_json(&req, etag_for(&body), body)
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
 }
 
 #[post("/recipes")]
-async fn add_recipe(data: web::Data<AppState>, item: web::Json<Recipe>) -> impl Responder {
-    let mut recipes = data.recipes.lock().unwrap();
-    recipes.insert(item.name.clone(), item.into_inner());
-    HttpResponse::Created().finish()
+async fn add_recipe(
+    data: web::Data<AppState>,
+    _user: AuthenticatedUser,
+    item: web::Json<RecipeInput>,
+) -> impl Responder {
+    let input = item.into_inner();
+    let recipe = Recipe {
+        id: Uuid::new_v4(),
+        name: input.name,
+        category: input.category,
+        method: input.method,
+        difficulty: input.difficulty,
+    };
+    let bytes = match serde_json::to_vec(&recipe) {
+        Ok(bytes) => bytes,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match data.recipes.insert(recipe.id.as_bytes(), bytes) {
+        Ok(_) => {
+            data.notify("created", recipe.id, recipe.name.clone());
+            HttpResponse::Created().json(recipe)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[put("/recipes/{id}")]
+async fn update_recipe(
+    data: web::Data<AppState>,
+    _user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    item: web::Json<RecipeInput>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match data.recipes.get(id.as_bytes()) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let input = item.into_inner();
+    let recipe = Recipe {
+        id,
+        name: input.name,
+        category: input.category,
+        method: input.method,
+        difficulty: input.difficulty,
+    };
+    let bytes = match serde_json::to_vec(&recipe) {
+        Ok(bytes) => bytes,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match data.recipes.insert(id.as_bytes(), bytes) {
+        Ok(_) => {
+            data.notify("updated", recipe.id, recipe.name.clone());
+            HttpResponse::Ok().json(recipe)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[delete("/recipes/{id}")]
+async fn delete_recipe(
+    data: web::Data<AppState>,
+    _user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match data.recipes.remove(id.as_bytes()) {
+        Ok(Some(value)) => {
+            let name = serde_json::from_slice::<Recipe>(&value)
+                .map(|recipe| recipe.name)
+                .unwrap_or_default();
+            data.notify("deleted", id, name);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/recipes/events")]
+async fn recipe_events(data: web::Data<AppState>) -> impl Responder {
+    let mut receiver = data.events.subscribe();
+    let stream = async_stream::stream! {
+        let mut keep_alive = tokio::time::interval(Duration::from_secs(15));
+        // The first tick fires immediately; skip it so we don't emit a comment up front.
+        keep_alive.tick().await;
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        if let Ok(payload) = serde_json::to_string(&event) {
+                            yield Ok::<_, actix_web::Error>(
+                                web::Bytes::from(format!("data: {payload}\n\n")),
+                            );
+                        }
+                    }
+                    // Lagged receivers skip ahead; a closed channel ends the stream.
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = keep_alive.tick() => {
+                    yield Ok(web::Bytes::from_static(b": keep-alive\n\n"));
+                }
+            }
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream)
+}
+
+/// Ensure at least one account exists so `/login` is usable on a fresh database,
+/// seeding the credentials from `ADMIN_USER`/`ADMIN_PASSWORD` (defaulting to
+/// `admin`/`admin`).
+fn seed_admin(users: &sled::Tree) {
+    if !users.is_empty() {
+        return;
+    }
+    let username = std::env::var("ADMIN_USER").unwrap_or_else(|_| "admin".to_string());
+    let password = std::env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "admin".to_string());
+    let salt: [u8; 16] = rand::random();
+    let password_hash = argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+        .expect("failed to hash admin password");
+    let user = User {
+        username: username.clone(),
+        password_hash,
+    };
+    let bytes = serde_json::to_vec(&user).expect("failed to serialize admin user");
+    users
+        .insert(username.as_bytes(), bytes)
+        .expect("failed to seed admin user");
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let db = sled::open("recipes.db").expect("failed to open sled database");
+    let recipes = db
+        .open_tree("recipes")
+        .expect("failed to open recipes tree");
+    let users = db.open_tree("users").expect("failed to open users tree");
+    seed_admin(&users);
+
+    let settings = Settings::load();
+    let (events, _) = broadcast::channel(64);
+
     let app_state = web::Data::new(AppState {
-        recipes: Mutex::new(HashMap::new()),
+        recipes,
+        users,
+        jwt_secret: settings.jwt_secret.clone(),
+        token_ttl: settings.token_ttl,
+        events,
+        list_cache: Mutex::new(None),
     });
+    let settings_data = web::Data::new(settings.clone());
+    let bind_host = settings.bind_host.clone();
+    let port = settings.port;
 
-    HttpServer::new(move || {
+    let result = HttpServer::new(move || {
+        let cors = Cors::default()
+            .allowed_origin(&settings.cors_origin)
+            .allow_any_method()
+            .allow_any_header();
         App::new()
+            .wrap(cors)
             .app_data(app_state.clone())
+            .app_data(settings_data.clone())
             .service(ping)
+            .service(login)
+            .service(me)
             .service(list_recipes)
+            .service(recipe_events)
+            .service(get_recipe)
             .service(add_recipe)
+            .service(update_recipe)
+            .service(delete_recipe)
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind((bind_host, port))?
     .run()
-    .await
+    .await;
+
+    // Persist any buffered writes before the process exits.
+    db.flush().expect("failed to flush sled database");
+    result
 }